@@ -0,0 +1,60 @@
+use crate::memory::MI;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DebugProbeError {
+    USBError,
+    ProbeFirmwareOutdated,
+    Timeout,
+    UnknownError,
+    CoreIndexOutOfBounds { index: u8, core_count: u8 },
+    FlashEraseAllUnsupported,
+    FlashVerifyMismatch { address: u32 },
+    ConfigKeyTooLong { len: usize },
+    ConfigValueTooLong { len: usize },
+    ConfigRegionNotSectorAligned { region_len: u32, sector_size: u32 },
+}
+
+impl From<std::io::Error> for DebugProbeError {
+    fn from(_: std::io::Error) -> Self {
+        DebugProbeError::USBError
+    }
+}
+
+/// Information about the core gathered as part of a halt/step operation,
+/// e.g. the program counter the core stopped at.
+#[derive(Debug, Copy, Clone)]
+pub struct CpuInformation {
+    pub pc: u32,
+}
+
+/// Wraps the probe driver and exposes the `MI` (memory interface) to the
+/// rest of the debugger, regardless of which physical probe is attached.
+pub struct MasterProbe {
+    actual_probe: Box<dyn MI>,
+}
+
+impl MasterProbe {
+    pub fn new(probe: Box<dyn MI>) -> Self {
+        Self {
+            actual_probe: probe,
+        }
+    }
+}
+
+impl MI for MasterProbe {
+    fn read32(&mut self, address: u32) -> Result<u32, DebugProbeError> {
+        self.actual_probe.read32(address)
+    }
+
+    fn write32(&mut self, address: u32, data: u32) -> Result<(), DebugProbeError> {
+        self.actual_probe.write32(address, data)
+    }
+
+    /// Bulk-reads as many bytes as are currently buffered from the probe's
+    /// trace (SWO) capture FIFO into `buf`, returning the number of bytes
+    /// actually read. Unlike `read32`/`write32` this is not addressed:
+    /// the probe firmware owns a single trace stream per session.
+    fn read_swo(&mut self, buf: &mut [u8]) -> Result<usize, DebugProbeError> {
+        self.actual_probe.read_swo(buf)
+    }
+}
@@ -0,0 +1,405 @@
+/// Describes a flash algorithm blob that can be loaded onto a target and
+/// executed from RAM to erase and program on-chip flash.
+///
+/// The `instructions` are position independent code, relocated to
+/// `load_address` before use. The `pc_*` fields are the entry points for
+/// the individual operations, expressed as absolute addresses once the
+/// blob has been loaded at `load_address`.
+pub struct FlashAlgorithm {
+    pub load_address: u32,
+    pub instructions: &'static [u32],
+    pub pc_init: Option<u32>,
+    pub pc_uninit: Option<u32>,
+    pub pc_program_page: u32,
+    pub pc_erase_sector: u32,
+    pub pc_erase_all: Option<u32>,
+    pub static_base: u32,
+    pub begin_stack: u32,
+    pub begin_data: u32,
+    pub page_buffers: &'static [u32],
+    pub min_program_length: u32,
+    pub analyzer_supported: bool,
+    pub analyzer_address: u32,
+}
+
+use crate::debug_probe::{DebugProbeError, MasterProbe};
+use crate::target::Target;
+use crate::memory::MI;
+
+/// Drives a `FlashAlgorithm` blob: loads it into RAM, calls into its
+/// entry points with a breakpoint at `load_address` acting as the return
+/// address, and lets a caller erase/program flash without knowing the
+/// underlying calling convention.
+pub struct FlashWriter<'a> {
+    target: &'a dyn Target,
+    algorithm: FlashAlgorithm,
+}
+
+impl<'a> FlashWriter<'a> {
+    pub fn new(target: &'a dyn Target, algorithm: FlashAlgorithm) -> Self {
+        Self { target, algorithm }
+    }
+
+    /// Copies the algorithm's instructions into RAM and arms a breakpoint
+    /// at `load_address`, which doubles as the return address every entry
+    /// point below is called with.
+    fn load(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        for (i, word) in self.algorithm.instructions.iter().enumerate() {
+            mi.write32(self.algorithm.load_address + (i as u32) * 4, *word)?;
+        }
+
+        self.target.set_breakpoint(mi, self.algorithm.load_address)
+    }
+
+    /// Points `PC` at `entry` and `LR` at `load_address`, runs, and waits
+    /// for the algorithm to return (hit the breakpoint set by `load`).
+    fn call(&self, mi: &mut MasterProbe, entry: u32) -> Result<(), DebugProbeError> {
+        let regs = self.target.get_basic_register_addresses();
+
+        self.target.write_core_reg(mi, regs.lr, self.algorithm.load_address)?;
+        self.target.write_core_reg(mi, regs.pc, entry)?;
+        self.target.run(mi)?;
+        self.target.wait_for_core_halted(mi)
+    }
+
+    /// Calls the algorithm's `Init` entry point, if it has one. Real
+    /// CMSIS-style flash algorithms use this to set up clocks/chip-select
+    /// before any erase/program operation.
+    fn init(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        match self.algorithm.pc_init {
+            Some(entry) => self.call(mi, entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls the algorithm's `Uninit` entry point, if it has one.
+    fn uninit(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        match self.algorithm.pc_uninit {
+            Some(entry) => self.call(mi, entry),
+            None => Ok(()),
+        }
+    }
+
+    /// Erases the sector containing `addr`.
+    pub fn erase_sector(&self, mi: &mut MasterProbe, addr: u32) -> Result<(), DebugProbeError> {
+        self.load(mi)?;
+        self.init(mi)?;
+
+        let regs = self.target.get_basic_register_addresses();
+        self.target.write_core_reg(mi, regs.r0, addr)?;
+
+        let result = self.call(mi, self.algorithm.pc_erase_sector);
+
+        // Always try to uninit, even if the erase itself failed, so a
+        // failed operation doesn't leave the algorithm's clocks/chip-select
+        // initialized for the next `load`+`init` to stumble over.
+        result.and(self.uninit(mi))
+    }
+
+    /// Mass-erases the whole flash, if the algorithm supports it.
+    pub fn erase_all(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        let entry = self
+            .algorithm
+            .pc_erase_all
+            .ok_or(DebugProbeError::FlashEraseAllUnsupported)?;
+
+        self.load(mi)?;
+        self.init(mi)?;
+        let result = self.call(mi, entry);
+
+        result.and(self.uninit(mi))
+    }
+
+    /// Programs `data` at `addr` through the algorithm's page buffer, then
+    /// reads the page back and compares it against `data`.
+    pub fn program_page(&self, mi: &mut MasterProbe, addr: u32, data: &[u8]) -> Result<(), DebugProbeError> {
+        self.load(mi)?;
+        self.init(mi)?;
+
+        let page_buffer = self.algorithm.page_buffers[0];
+        write_bytes(mi, page_buffer, data)?;
+
+        let regs = self.target.get_basic_register_addresses();
+        self.target.write_core_reg(mi, regs.r0, addr)?;
+        self.target.write_core_reg(mi, regs.r1, data.len() as u32)?;
+        self.target.write_core_reg(mi, regs.r2, page_buffer)?;
+        let result = self
+            .call(mi, self.algorithm.pc_program_page)
+            .and_then(|()| verify(mi, addr, data));
+
+        result.and(self.uninit(mi))
+    }
+}
+
+/// Writes `data` to `addr` a word at a time, since `MI` only exposes
+/// 32-bit addressed accesses.
+fn write_bytes(mi: &mut MasterProbe, addr: u32, data: &[u8]) -> Result<(), DebugProbeError> {
+    for (i, chunk) in data.chunks(4).enumerate() {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        mi.write32(addr + (i as u32) * 4, u32::from_le_bytes(word))?;
+    }
+    Ok(())
+}
+
+/// Reads `buf.len()` bytes starting at `addr` a word at a time.
+fn read_bytes(mi: &mut MasterProbe, addr: u32, buf: &mut [u8]) -> Result<(), DebugProbeError> {
+    for (i, chunk) in buf.chunks_mut(4).enumerate() {
+        let word = mi.read32(addr + (i as u32) * 4)?.to_le_bytes();
+        chunk.copy_from_slice(&word[..chunk.len()]);
+    }
+    Ok(())
+}
+
+/// Reads back `expected.len()` bytes from `addr` and compares them
+/// against `expected`, failing on the first mismatched word.
+fn verify(mi: &mut MasterProbe, addr: u32, expected: &[u8]) -> Result<(), DebugProbeError> {
+    let mut actual = vec![0u8; expected.len()];
+    read_bytes(mi, addr, &mut actual)?;
+
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(DebugProbeError::FlashVerifyMismatch { address: addr })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    Value,
+    Tombstone,
+}
+
+/// A durable key/value store for small config values (e.g. calibration or
+/// network settings), implemented as an append-only log inside a
+/// dedicated flash sector range.
+///
+/// Flash can only be rewritten after a full sector erase, so `write` and
+/// `remove` append new records rather than mutating in place; `compact`
+/// is what actually reclaims the space used by stale records, by erasing
+/// the region and replaying only the latest value of each key.
+pub struct ConfigStore<'a> {
+    writer: FlashWriter<'a>,
+    region_start: u32,
+    region_end: u32,
+    sector_size: u32,
+    write_cursor: u32,
+}
+
+impl<'a> ConfigStore<'a> {
+    /// Reserves `region_start..region_end` as the config store's backing
+    /// storage, erased and reclaimed one `sector_size` sector at a time.
+    /// `region_end - region_start` must be a whole number of sectors, or
+    /// `compact` wouldn't be able to erase the region it is responsible
+    /// for. The region is assumed to already be erased (all `0xFF`); call
+    /// `compact` first if reusing a region that may hold old data.
+    pub fn new(
+        writer: FlashWriter<'a>,
+        region_start: u32,
+        region_end: u32,
+        sector_size: u32,
+    ) -> Result<Self, DebugProbeError> {
+        let region_len = region_end - region_start;
+        if sector_size == 0 || !region_len.is_multiple_of(sector_size) {
+            return Err(DebugProbeError::ConfigRegionNotSectorAligned { region_len, sector_size });
+        }
+
+        Ok(Self {
+            writer,
+            region_start,
+            region_end,
+            sector_size,
+            write_cursor: region_start,
+        })
+    }
+
+    pub fn write(&mut self, mi: &mut MasterProbe, key: &str, value: &[u8]) -> Result<(), DebugProbeError> {
+        self.append(mi, RecordKind::Value, key, value)
+    }
+
+    pub fn remove(&mut self, mi: &mut MasterProbe, key: &str) -> Result<(), DebugProbeError> {
+        self.append(mi, RecordKind::Tombstone, key, &[])
+    }
+
+    /// Reads the latest non-removed value for `key`, if any, by scanning
+    /// the log from the start of the region.
+    pub fn read(&self, mi: &mut MasterProbe, key: &str) -> Result<Option<Vec<u8>>, DebugProbeError> {
+        let mut latest = None;
+        let mut cursor = self.region_start;
+
+        while cursor < self.write_cursor {
+            let (kind, record_key, value, record_len) = self.read_record(mi, cursor)?;
+
+            if record_key == key {
+                latest = match kind {
+                    RecordKind::Value => Some(value),
+                    RecordKind::Tombstone => None,
+                };
+            }
+
+            cursor += record_len;
+        }
+
+        Ok(latest)
+    }
+
+    /// Erases the whole region and rewrites only the latest value of
+    /// every key still live, reclaiming the space used by overwritten or
+    /// removed records.
+    pub fn compact(&mut self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        let mut live: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut cursor = self.region_start;
+
+        while cursor < self.write_cursor {
+            let (kind, key, value, record_len) = self.read_record(mi, cursor)?;
+
+            live.retain(|(existing_key, _)| existing_key != &key);
+            if kind == RecordKind::Value {
+                live.push((key, value));
+            }
+
+            cursor += record_len;
+        }
+
+        let mut sector = self.region_start;
+        while sector < self.region_end {
+            self.writer.erase_sector(mi, sector)?;
+            sector += self.sector_size;
+        }
+        self.write_cursor = self.region_start;
+
+        for (key, value) in live {
+            self.write(mi, &key, &value)?;
+        }
+
+        Ok(())
+    }
+
+    fn append(&mut self, mi: &mut MasterProbe, kind: RecordKind, key: &str, value: &[u8]) -> Result<(), DebugProbeError> {
+        let record = encode_record(kind, key, value)?;
+
+        if self.write_cursor + record.len() as u32 > self.region_end {
+            self.compact(mi)?;
+        }
+
+        self.writer.program_page(mi, self.write_cursor, &record)?;
+        self.write_cursor += record.len() as u32;
+
+        Ok(())
+    }
+
+    /// Parses the record at `addr`, returning its kind, key, value and
+    /// total length in bytes (rounded up to a 4-byte boundary, matching the
+    /// padding `encode_record` writes) so the caller can advance past it.
+    fn read_record(&self, mi: &mut MasterProbe, addr: u32) -> Result<(RecordKind, String, Vec<u8>, u32), DebugProbeError> {
+        let mut header = [0u8; 4];
+        read_bytes(mi, addr, &mut header)?;
+
+        let (kind, key_len, value_len) = decode_record_header(header);
+
+        let mut body = vec![0u8; (key_len + value_len) as usize];
+        read_bytes(mi, addr + 4, &mut body)?;
+
+        let key = String::from_utf8_lossy(&body[..key_len as usize]).into_owned();
+        let value = body[key_len as usize..].to_vec();
+
+        Ok((kind, key, value, round_up_4(4 + key_len + value_len)))
+    }
+}
+
+/// Rounds `n` up to the nearest multiple of 4.
+fn round_up_4(n: u32) -> u32 {
+    (n + 3) & !3
+}
+
+/// Builds the on-flash bytes for a single log record: a 4-byte header
+/// (kind, key length, value length) followed by the key and value bytes,
+/// zero-padded up to a 4-byte boundary, since `write_bytes`/`read_bytes`
+/// (and the underlying `MI`) only do word-granular accesses.
+fn encode_record(kind: RecordKind, key: &str, value: &[u8]) -> Result<Vec<u8>, DebugProbeError> {
+    if key.len() > usize::from(u8::MAX) {
+        return Err(DebugProbeError::ConfigKeyTooLong { len: key.len() });
+    }
+    if value.len() > usize::from(u16::MAX) {
+        return Err(DebugProbeError::ConfigValueTooLong { len: value.len() });
+    }
+
+    let padded_len = round_up_4((4 + key.len() + value.len()) as u32) as usize;
+
+    let mut record = Vec::with_capacity(padded_len);
+    record.push(kind as u8);
+    record.push(key.len() as u8);
+    record.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(value);
+    record.resize(padded_len, 0);
+
+    Ok(record)
+}
+
+/// Parses a record's 4-byte header into its kind, key length and value
+/// length.
+fn decode_record_header(header: [u8; 4]) -> (RecordKind, u32, u32) {
+    let kind = if header[0] == RecordKind::Tombstone as u8 {
+        RecordKind::Tombstone
+    } else {
+        RecordKind::Value
+    };
+    let key_len = u32::from(header[1]);
+    let value_len = u32::from(u16::from_le_bytes([header[2], header[3]]));
+
+    (kind, key_len, value_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_record_to_a_4_byte_boundary() {
+        // Header (4) + key "k" (1) + value "ab" (2) = 7 bytes, rounds up to 8.
+        let record = encode_record(RecordKind::Value, "k", b"ab").unwrap();
+
+        assert_eq!(record.len(), 8);
+        assert_eq!(&record[7..8], &[0]);
+    }
+
+    #[test]
+    fn header_round_trips_through_encode_and_decode() {
+        let record = encode_record(RecordKind::Tombstone, "key", b"value").unwrap();
+
+        let mut header = [0u8; 4];
+        header.copy_from_slice(&record[..4]);
+        let (kind, key_len, value_len) = decode_record_header(header);
+
+        assert_eq!(kind, RecordKind::Tombstone);
+        assert_eq!(key_len, 3);
+        assert_eq!(value_len, 5);
+    }
+
+    #[test]
+    fn rejects_an_oversized_key() {
+        let key = "k".repeat(usize::from(u8::MAX) + 1);
+
+        let err = encode_record(RecordKind::Value, &key, &[]).unwrap_err();
+
+        assert!(matches!(err, DebugProbeError::ConfigKeyTooLong { len } if len == key.len()));
+    }
+
+    #[test]
+    fn rejects_an_oversized_value() {
+        let value = vec![0u8; usize::from(u16::MAX) + 1];
+
+        let err = encode_record(RecordKind::Value, "k", &value).unwrap_err();
+
+        assert!(matches!(err, DebugProbeError::ConfigValueTooLong { len } if len == value.len()));
+    }
+
+    #[test]
+    fn round_up_4_rounds_to_the_next_multiple_of_4() {
+        assert_eq!(round_up_4(0), 0);
+        assert_eq!(round_up_4(1), 4);
+        assert_eq!(round_up_4(4), 4);
+        assert_eq!(round_up_4(5), 8);
+    }
+}
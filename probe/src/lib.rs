@@ -0,0 +1,5 @@
+pub mod debug_probe;
+pub mod flash_writer;
+pub mod memory;
+pub mod target;
+pub mod trace;
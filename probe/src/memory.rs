@@ -0,0 +1,11 @@
+use crate::debug_probe::DebugProbeError;
+
+/// The memory interface: addressed 32-bit accesses to a target's memory
+/// map, plus the probe-specific trace (SWO) byte stream. Implemented by
+/// each probe driver and by `MasterProbe`, which forwards to whichever
+/// driver is actually attached.
+pub trait MI {
+    fn read32(&mut self, address: u32) -> Result<u32, DebugProbeError>;
+    fn write32(&mut self, address: u32, data: u32) -> Result<(), DebugProbeError>;
+    fn read_swo(&mut self, buf: &mut [u8]) -> Result<usize, DebugProbeError>;
+}
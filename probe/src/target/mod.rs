@@ -0,0 +1,243 @@
+pub mod m0;
+pub mod thumb;
+
+use crate::debug_probe::{CpuInformation, DebugProbeError, MasterProbe};
+use crate::flash_writer::FlashAlgorithm;
+
+/// A register on the target's debug register map, e.g. `DHCSR` or `DEMCR`.
+/// Implementors wrap a single `u32` and know their own address so they can
+/// be read/written through a `MasterProbe` without repeating the address
+/// at every call site.
+pub trait TargetRegister: Into<u32> + From<u32> + Copy + Clone {
+    const ADDRESS: u32;
+    const NAME: &'static str;
+}
+
+/// Address of a core register as understood by `DCRSR.REGSEL`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CoreRegisterAddress(pub u8);
+
+impl From<CoreRegisterAddress> for u32 {
+    fn from(value: CoreRegisterAddress) -> Self {
+        u32::from(value.0)
+    }
+}
+
+pub struct BasicRegisterAddresses {
+    pub r0: CoreRegisterAddress,
+    pub r1: CoreRegisterAddress,
+    pub r2: CoreRegisterAddress,
+    pub r3: CoreRegisterAddress,
+    pub r9: CoreRegisterAddress,
+    pub pc: CoreRegisterAddress,
+    pub lr: CoreRegisterAddress,
+    pub sp: CoreRegisterAddress,
+}
+
+/// The debug operations a core needs to implement to be usable by the rest
+/// of the debugger. One implementation exists per core architecture (e.g.
+/// `m0::M0`).
+pub trait Target {
+    fn get_flash_algorithm(&self) -> FlashAlgorithm;
+    fn get_basic_register_addresses(&self) -> BasicRegisterAddresses;
+
+    fn wait_for_core_halted(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError>;
+
+    fn read_core_reg(
+        &self,
+        mi: &mut MasterProbe,
+        addr: CoreRegisterAddress,
+    ) -> Result<u32, DebugProbeError>;
+    fn write_core_reg(
+        &self,
+        mi: &mut MasterProbe,
+        addr: CoreRegisterAddress,
+        value: u32,
+    ) -> Result<(), DebugProbeError>;
+
+    fn halt(&self, mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError>;
+    fn run(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError>;
+    fn step(&self, mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError>;
+    /// Like `step`, but if the instruction under `PC` is a call, runs to
+    /// the instruction after it instead of single-stepping into the
+    /// callee.
+    fn step_over(&self, mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError>;
+    /// Halts the core the instant it comes out of reset, via the
+    /// `VC_CORERESET` vector catch, instead of relying on it already
+    /// being running.
+    fn reset_and_halt(&self, mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError>;
+
+    fn get_available_breakpoint_units(&self, mi: &mut MasterProbe) -> Result<u32, DebugProbeError>;
+    fn enable_breakpoints(&self, mi: &mut MasterProbe, state: bool) -> Result<(), DebugProbeError>;
+    fn set_breakpoint(&self, mi: &mut MasterProbe, addr: u32) -> Result<(), DebugProbeError>;
+    fn enable_breakpoint(&self, mi: &mut MasterProbe, addr: u32) -> Result<(), DebugProbeError>;
+    fn disable_breakpoint(&self, mi: &mut MasterProbe, addr: u32) -> Result<(), DebugProbeError>;
+}
+
+/// A set of same-architecture cores reachable through the same access
+/// port (e.g. several Cortex-M0 cores, or a core plus a coprocessor),
+/// letting a caller enumerate them and address each one independently.
+pub struct MultiCore<T: Target> {
+    cores: Vec<T>,
+    selected: usize,
+}
+
+impl<T: Target> MultiCore<T> {
+    pub fn new(cores: Vec<T>) -> Self {
+        Self { cores, selected: 0 }
+    }
+
+    pub fn core_count(&self) -> usize {
+        self.cores.len()
+    }
+
+    /// Makes `index` the target of `selected()`. Does not itself touch the
+    /// probe; debug operations still need to be issued against the
+    /// returned core.
+    pub fn select_core(&mut self, index: usize) -> Result<(), DebugProbeError> {
+        if index >= self.cores.len() {
+            return Err(DebugProbeError::CoreIndexOutOfBounds {
+                index: index as u8,
+                core_count: self.cores.len() as u8,
+            });
+        }
+
+        self.selected = index;
+        Ok(())
+    }
+
+    pub fn selected(&self) -> &T {
+        &self.cores[self.selected]
+    }
+
+    /// Halts every core in turn.
+    pub fn halt_all(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        for core in &self.cores {
+            core.halt(mi)?;
+        }
+        Ok(())
+    }
+
+    /// Resumes every core in turn.
+    pub fn run_all(&self, mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+        for core in &self.cores {
+            core.run(mi)?;
+        }
+        Ok(())
+    }
+
+    /// Halts every core other than `halted_index`, modeling a
+    /// software-triggered cross-core halt: one core stopping (e.g. on a
+    /// breakpoint) requests that its siblings stop too.
+    pub fn halt_others(&self, mi: &mut MasterProbe, halted_index: usize) -> Result<(), DebugProbeError> {
+        for (index, core) in self.cores.iter().enumerate() {
+            if index != halted_index {
+                core.halt(mi)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `Target` stub that only exists to give `MultiCore` something to
+    /// hold; `select_core`/`core_count`/`selected` never touch the probe,
+    /// so every method here is unreachable from these tests.
+    struct StubCore;
+
+    impl Target for StubCore {
+        fn get_flash_algorithm(&self) -> FlashAlgorithm {
+            unimplemented!()
+        }
+        fn get_basic_register_addresses(&self) -> BasicRegisterAddresses {
+            unimplemented!()
+        }
+        fn wait_for_core_halted(&self, _mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+        fn read_core_reg(
+            &self,
+            _mi: &mut MasterProbe,
+            _addr: CoreRegisterAddress,
+        ) -> Result<u32, DebugProbeError> {
+            unimplemented!()
+        }
+        fn write_core_reg(
+            &self,
+            _mi: &mut MasterProbe,
+            _addr: CoreRegisterAddress,
+            _value: u32,
+        ) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+        fn halt(&self, _mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError> {
+            unimplemented!()
+        }
+        fn run(&self, _mi: &mut MasterProbe) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+        fn step(&self, _mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError> {
+            unimplemented!()
+        }
+        fn step_over(&self, _mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError> {
+            unimplemented!()
+        }
+        fn reset_and_halt(&self, _mi: &mut MasterProbe) -> Result<CpuInformation, DebugProbeError> {
+            unimplemented!()
+        }
+        fn get_available_breakpoint_units(&self, _mi: &mut MasterProbe) -> Result<u32, DebugProbeError> {
+            unimplemented!()
+        }
+        fn enable_breakpoints(&self, _mi: &mut MasterProbe, _state: bool) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+        fn set_breakpoint(&self, _mi: &mut MasterProbe, _addr: u32) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+        fn enable_breakpoint(&self, _mi: &mut MasterProbe, _addr: u32) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+        fn disable_breakpoint(&self, _mi: &mut MasterProbe, _addr: u32) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+    }
+
+    fn multi_core(count: usize) -> MultiCore<StubCore> {
+        MultiCore::new((0..count).map(|_| StubCore).collect())
+    }
+
+    #[test]
+    fn reports_the_number_of_cores() {
+        assert_eq!(multi_core(3).core_count(), 3);
+    }
+
+    #[test]
+    fn selected_defaults_to_the_first_core() {
+        let cores = multi_core(2);
+        assert!(std::ptr::eq(cores.selected(), &cores.cores[0]));
+    }
+
+    #[test]
+    fn select_core_switches_the_selected_core() {
+        let mut cores = multi_core(2);
+
+        cores.select_core(1).unwrap();
+
+        assert!(std::ptr::eq(cores.selected(), &cores.cores[1]));
+    }
+
+    #[test]
+    fn select_core_rejects_an_out_of_bounds_index() {
+        let mut cores = multi_core(2);
+
+        let result = cores.select_core(2);
+
+        assert_eq!(
+            result,
+            Err(DebugProbeError::CoreIndexOutOfBounds { index: 2, core_count: 2 })
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! A small Thumb instruction decoder, just deep enough to tell `step_over`
+//! whether the instruction under the program counter is a call (so it
+//! should run to the return address instead of single-stepping into it).
+
+/// What a decoded instruction means for stepping purposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbInstructionKind {
+    /// `B` (conditional or unconditional): branches, does not push a
+    /// return address.
+    Branch { target: u32 },
+    /// `BL`/`BLX` (immediate): a call to a PC-relative target.
+    Call { target: u32 },
+    /// `BX`/`BLX` (register): an indirect branch, or call if `is_call`.
+    BranchExchange { is_call: bool },
+    /// `POP` with `PC` in the register list: a function return.
+    PopPc,
+    /// Anything this decoder doesn't need to distinguish further.
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThumbInstruction {
+    pub kind: ThumbInstructionKind,
+    /// Encoded length in bytes: 2 for 16-bit Thumb, 4 for 32-bit Thumb-2.
+    pub length: u8,
+}
+
+impl ThumbInstruction {
+    /// Whether stepping over this instruction should run to completion
+    /// rather than single-step into it.
+    pub fn is_call(&self) -> bool {
+        match self.kind {
+            ThumbInstructionKind::Call { .. } => true,
+            ThumbInstructionKind::BranchExchange { is_call } => is_call,
+            _ => false,
+        }
+    }
+
+    pub fn is_return(&self) -> bool {
+        matches!(
+            self.kind,
+            ThumbInstructionKind::PopPc | ThumbInstructionKind::BranchExchange { is_call: false }
+        )
+    }
+
+    /// The branch target, for instructions whose destination is encoded
+    /// as a PC-relative immediate (`B`, `BL`/`BLX` immediate). `pc` should
+    /// be the address of this instruction.
+    pub fn branch_target(&self, pc: u32) -> Option<u32> {
+        match self.kind {
+            ThumbInstructionKind::Branch { target } | ThumbInstructionKind::Call { target } => {
+                Some(pc.wrapping_add(4).wrapping_add(target))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the Thumb instruction at the front of `bytes`, which must
+/// contain at least 2 bytes (4 if the first halfword turns out to begin a
+/// 32-bit Thumb-2 encoding).
+pub fn decode(bytes: &[u8]) -> ThumbInstruction {
+    let hw0 = u16::from_le_bytes([bytes[0], bytes[1]]);
+
+    // 32-bit Thumb-2 instructions are distinguished by bits[15:11] of the
+    // first halfword being 0b11101, 0b11110 or 0b11111.
+    let op0 = hw0 >> 11;
+    if op0 == 0b11101 || op0 == 0b11110 || op0 == 0b11111 {
+        let hw1 = u16::from_le_bytes([bytes[2], bytes[3]]);
+        return decode_32bit(hw0, hw1);
+    }
+
+    decode_16bit(hw0)
+}
+
+fn decode_16bit(hw: u16) -> ThumbInstruction {
+    // B<cond> <label>: 1101 cccc iiiiiiii (cond 1110/1111 are UNDEFINED/SVC)
+    if hw >> 12 == 0b1101 {
+        let cond = (hw >> 8) & 0xF;
+        if cond != 0b1110 && cond != 0b1111 {
+            let imm8 = (hw & 0xFF) as i8 as i32;
+            return ThumbInstruction {
+                kind: ThumbInstructionKind::Branch {
+                    target: (imm8 << 1) as u32,
+                },
+                length: 2,
+            };
+        }
+    }
+
+    // B <label> (unconditional): 11100 iiiiiiiiiii
+    if hw >> 11 == 0b11100 {
+        let imm11 = hw & 0x7FF;
+        let signed = sign_extend(u32::from(imm11) << 1, 12);
+        return ThumbInstruction {
+            kind: ThumbInstructionKind::Branch { target: signed },
+            length: 2,
+        };
+    }
+
+    // BX/BLX (register): 0100 0111 L mmmm 000
+    if hw >> 8 == 0b0100_0111 {
+        let is_call = (hw >> 7) & 1 != 0;
+        return ThumbInstruction {
+            kind: ThumbInstructionKind::BranchExchange { is_call },
+            length: 2,
+        };
+    }
+
+    // POP {reglist, PC}: 1011 110 P rrrrrrrr
+    if hw >> 9 == 0b101_1110 {
+        let includes_pc = (hw >> 8) & 1 != 0;
+        if includes_pc {
+            return ThumbInstruction {
+                kind: ThumbInstructionKind::PopPc,
+                length: 2,
+            };
+        }
+    }
+
+    ThumbInstruction {
+        kind: ThumbInstructionKind::Other,
+        length: 2,
+    }
+}
+
+// BL/BLX (immediate): a 32-bit pair.
+// hw0: 11110 S iiiiiiiiii
+// hw1: 11 J1 x J2 iiiiiiiiiii  (x = 1 for BL, 0 for BLX immediate)
+fn decode_32bit(hw0: u16, hw1: u16) -> ThumbInstruction {
+    if hw0 >> 11 == 0b11110 && hw1 >> 14 == 0b11 {
+        let s = u32::from((hw0 >> 10) & 1);
+        let imm10 = u32::from(hw0 & 0x3FF);
+        let j1 = u32::from((hw1 >> 13) & 1);
+        let j2 = u32::from((hw1 >> 11) & 1);
+        let imm11 = u32::from(hw1 & 0x7FF);
+
+        let i1 = 1 - (j1 ^ s);
+        let i2 = 1 - (j2 ^ s);
+
+        let imm = (s << 24) | (i1 << 23) | (i2 << 22) | (imm10 << 12) | (imm11 << 1);
+        let target = sign_extend(imm, 25);
+
+        return ThumbInstruction {
+            kind: ThumbInstructionKind::Call { target },
+            length: 4,
+        };
+    }
+
+    ThumbInstruction {
+        kind: ThumbInstructionKind::Other,
+        length: 4,
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> u32 {
+    let shift = 32 - bits;
+    (((value << shift) as i32) >> shift) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_conditional_branch() {
+        // B<cond>: cond 0 (EQ), imm8 = 2 -> target = 2 << 1 = 4.
+        let instruction = decode(&0xD002u16.to_le_bytes());
+
+        assert_eq!(
+            instruction,
+            ThumbInstruction { kind: ThumbInstructionKind::Branch { target: 4 }, length: 2 }
+        );
+        assert!(!instruction.is_call());
+    }
+
+    #[test]
+    fn decodes_bl_immediate_as_a_call() {
+        let mut bytes = [0u8; 4];
+        bytes[0..2].copy_from_slice(&0xF000u16.to_le_bytes());
+        bytes[2..4].copy_from_slice(&0xF800u16.to_le_bytes());
+
+        let instruction = decode(&bytes);
+
+        assert_eq!(
+            instruction,
+            ThumbInstruction { kind: ThumbInstructionKind::Call { target: 0 }, length: 4 }
+        );
+        assert!(instruction.is_call());
+    }
+
+    #[test]
+    fn decodes_bx_register_as_a_return() {
+        // BX R0: 0100 0111 0 00000 000.
+        let instruction = decode(&0x4700u16.to_le_bytes());
+
+        assert_eq!(
+            instruction,
+            ThumbInstruction { kind: ThumbInstructionKind::BranchExchange { is_call: false }, length: 2 }
+        );
+        assert!(instruction.is_return());
+    }
+
+    #[test]
+    fn decodes_pop_pc_as_a_return() {
+        // POP {PC}: 1011 110 1 00000000.
+        let instruction = decode(&0xBD00u16.to_le_bytes());
+
+        assert_eq!(instruction, ThumbInstruction { kind: ThumbInstructionKind::PopPc, length: 2 });
+        assert!(instruction.is_return());
+    }
+
+    #[test]
+    fn treats_0b11101_prefix_as_a_32bit_instruction() {
+        // First halfword with bits[15:11] == 0b11101, not a BL/BLX encoding.
+        let bytes = [0x00, 0b1110_1000u8, 0x00, 0x00];
+
+        let instruction = decode(&bytes);
+
+        assert_eq!(instruction.length, 4);
+        assert_eq!(instruction.kind, ThumbInstructionKind::Other);
+    }
+}
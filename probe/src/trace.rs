@@ -0,0 +1,367 @@
+//! ITM/SWO trace capture.
+//!
+//! This is a subsystem parallel to the `Target` debug methods: instead of
+//! halting/stepping a core, it configures the DWT/ITM/TPIU blocks for
+//! streaming trace output and decodes the byte stream the probe hands
+//! back over SWO into a sequence of `TracePacket`s.
+
+use crate::debug_probe::{DebugProbeError, MasterProbe};
+use crate::target::m0::Demcr;
+use crate::target::TargetRegister;
+use bitfield::bitfield;
+use crate::memory::MI;
+
+bitfield! {
+    #[derive(Copy, Clone)]
+    pub struct ItmTer(u32);
+    impl Debug;
+    /// One enable bit per stimulus port (0..=31).
+    pub _, set_ports: 31, 0;
+}
+
+impl From<u32> for ItmTer {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ItmTer> for u32 {
+    fn from(value: ItmTer) -> Self {
+        value.0
+    }
+}
+
+impl TargetRegister for ItmTer {
+    const ADDRESS: u32 = 0xE000_0E00;
+    const NAME: &'static str = "ITM_TER";
+}
+
+bitfield! {
+    #[derive(Copy, Clone)]
+    pub struct ItmTpr(u32);
+    impl Debug;
+    /// One privilege bit per group of 8 stimulus ports. Clear = unprivileged
+    /// access allowed.
+    pub _, set_privmask: 3, 0;
+}
+
+impl From<u32> for ItmTpr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ItmTpr> for u32 {
+    fn from(value: ItmTpr) -> Self {
+        value.0
+    }
+}
+
+impl TargetRegister for ItmTpr {
+    const ADDRESS: u32 = 0xE000_0E40;
+    const NAME: &'static str = "ITM_TPR";
+}
+
+bitfield! {
+    #[derive(Copy, Clone)]
+    pub struct ItmTcr(u32);
+    impl Debug;
+    /// Trace bus ID used to tag packets from this ITM on a shared trace bus.
+    pub _, set_tracebusid: 22, 16;
+    /// Enables the DWT to generate hardware source packets.
+    pub _, set_dwtena: 3;
+    /// Enables use of the stimulus ports.
+    pub _, set_itmena: 0;
+}
+
+impl From<u32> for ItmTcr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ItmTcr> for u32 {
+    fn from(value: ItmTcr) -> Self {
+        value.0
+    }
+}
+
+impl TargetRegister for ItmTcr {
+    const ADDRESS: u32 = 0xE000_0E80;
+    const NAME: &'static str = "ITM_TCR";
+}
+
+bitfield! {
+    #[derive(Copy, Clone)]
+    pub struct TpiuSppr(u32);
+    impl Debug;
+    /// Selected pin protocol: 1 = SWO using NRZ (UART) encoding, 2 = SWO
+    /// using Manchester encoding.
+    pub _, set_txmode: 1, 0;
+}
+
+impl From<u32> for TpiuSppr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<TpiuSppr> for u32 {
+    fn from(value: TpiuSppr) -> Self {
+        value.0
+    }
+}
+
+impl TargetRegister for TpiuSppr {
+    const ADDRESS: u32 = 0xE004_00F0;
+    const NAME: &'static str = "TPIU_SPPR";
+}
+
+bitfield! {
+    #[derive(Copy, Clone)]
+    pub struct TpiuAcpr(u32);
+    impl Debug;
+    /// SWOSCALER: the SWO baud rate is the TPIU's input clock divided by
+    /// `SWOSCALER + 1`.
+    pub _, set_swoscaler: 15, 0;
+}
+
+impl From<u32> for TpiuAcpr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<TpiuAcpr> for u32 {
+    fn from(value: TpiuAcpr) -> Self {
+        value.0
+    }
+}
+
+impl TargetRegister for TpiuAcpr {
+    const ADDRESS: u32 = 0xE004_0010;
+    const NAME: &'static str = "TPIU_ACPR";
+}
+
+const SWO_NRZ: u32 = 0b10;
+
+/// Enables `TRCENA`, turns on the DWT and all 32 ITM stimulus ports at
+/// unprivileged access, and configures the TPIU for NRZ SWO output at
+/// `swo_prescaler` (see `TpiuAcpr`).
+pub fn configure_trace(mi: &mut MasterProbe, swo_prescaler: u32) -> Result<(), DebugProbeError> {
+    let mut demcr = Demcr(mi.read32(Demcr::ADDRESS)?);
+    demcr.set_trcena(true);
+    mi.write32(Demcr::ADDRESS, demcr.into())?;
+
+    let mut ter = ItmTer(0);
+    ter.set_ports(0xFFFF_FFFF);
+    mi.write32(ItmTer::ADDRESS, ter.into())?;
+
+    let mut tpr = ItmTpr(0);
+    tpr.set_privmask(0);
+    mi.write32(ItmTpr::ADDRESS, tpr.into())?;
+
+    let mut tcr = ItmTcr(0);
+    tcr.set_tracebusid(1);
+    tcr.set_dwtena(true);
+    tcr.set_itmena(true);
+    mi.write32(ItmTcr::ADDRESS, tcr.into())?;
+
+    let mut sppr = TpiuSppr(0);
+    sppr.set_txmode(SWO_NRZ);
+    mi.write32(TpiuSppr::ADDRESS, sppr.into())?;
+
+    let mut acpr = TpiuAcpr(0);
+    acpr.set_swoscaler(swo_prescaler);
+    mi.write32(TpiuAcpr::ADDRESS, acpr.into())?;
+
+    Ok(())
+}
+
+/// A decoded ITM/DWT trace frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TracePacket {
+    /// A write to an ITM stimulus (software) port.
+    Instrumentation { port: u8, payload: Vec<u8> },
+    /// A DWT periodic PC sample.
+    PcSample { pc: u32 },
+    /// A DWT exception entry/exit/return event.
+    Exception { exception_number: u16, event: ExceptionEvent },
+    /// A DWT data-trace read or write generated by a watchpoint comparator.
+    DataTrace { comparator: u8, access: DataTraceAccess, value: Vec<u8> },
+    /// A frame the decoder does not interpret further, e.g. a
+    /// synchronization or overflow packet.
+    Unknown(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionEvent {
+    Enter,
+    Exit,
+    Return,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataTraceAccess {
+    Read,
+    Write,
+}
+
+/// Reassembles the byte stream read from the trace buffer into
+/// `TracePacket`s, buffering a trailing partial frame across calls.
+pub struct TraceDecoder {
+    partial: Vec<u8>,
+}
+
+impl Default for TraceDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceDecoder {
+    pub fn new() -> Self {
+        Self { partial: Vec::new() }
+    }
+
+    /// Feeds newly captured bytes into the decoder and returns every
+    /// packet that could be fully reassembled. Bytes belonging to a frame
+    /// that hasn't arrived in full yet are kept for the next call.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<TracePacket> {
+        self.partial.extend_from_slice(bytes);
+
+        let mut packets = Vec::new();
+        let mut offset = 0;
+
+        while offset < self.partial.len() {
+            let header = self.partial[offset];
+
+            // bits[1:0] == 00 marks a synchronization, overflow or
+            // protocol packet rather than a source packet with a payload.
+            if header & 0b11 == 0b00 {
+                packets.push(TracePacket::Unknown(vec![header]));
+                offset += 1;
+                continue;
+            }
+
+            let payload_len = match header & 0b11 {
+                0b01 => 1,
+                0b10 => 2,
+                0b11 => 4,
+                _ => unreachable!(),
+            };
+
+            if offset + 1 + payload_len > self.partial.len() {
+                // Incomplete frame; wait for the rest to arrive.
+                break;
+            }
+
+            let source = header >> 3;
+            let is_hardware_source = header & 0b100 != 0;
+            let payload = self.partial[offset + 1..offset + 1 + payload_len].to_vec();
+
+            packets.push(if is_hardware_source {
+                decode_hardware_packet(source, payload)
+            } else {
+                TracePacket::Instrumentation { port: source, payload }
+            });
+
+            offset += 1 + payload_len;
+        }
+
+        self.partial.drain(..offset);
+        packets
+    }
+}
+
+fn decode_hardware_packet(source: u8, payload: Vec<u8>) -> TracePacket {
+    match source {
+        // DWT source 0: exception trace. Payload is a little-endian u16
+        // with the exception number in bits[11:0] and the event kind in
+        // bits[13:12] (1 = enter, 2 = exit, 3 = return).
+        0 if payload.len() == 2 => {
+            let raw = u16::from_le_bytes([payload[0], payload[1]]);
+            let exception_number = raw & 0x0FFF;
+            let event = match (raw >> 12) & 0b11 {
+                1 => ExceptionEvent::Enter,
+                2 => ExceptionEvent::Exit,
+                _ => ExceptionEvent::Return,
+            };
+            TracePacket::Exception { exception_number, event }
+        }
+        // DWT source 2: periodic PC sample.
+        2 if payload.len() == 4 => {
+            let pc = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            TracePacket::PcSample { pc }
+        }
+        // DWT sources 8..=23: data trace, pairs of (address, data value)
+        // packets per comparator; even offset = write, odd offset = read.
+        8..=23 => {
+            let comparator = (source - 8) / 2;
+            let access = if (source - 8).is_multiple_of(2) {
+                DataTraceAccess::Write
+            } else {
+                DataTraceAccess::Read
+            };
+            TracePacket::DataTrace { comparator, access, value: payload }
+        }
+        _ => TracePacket::Unknown(payload),
+    }
+}
+
+/// Drains whatever the probe currently has buffered from the trace FIFO
+/// and feeds it into `decoder`, returning the packets that became
+/// available.
+pub fn poll_trace(
+    mi: &mut MasterProbe,
+    decoder: &mut TraceDecoder,
+    scratch: &mut [u8],
+) -> Result<Vec<TracePacket>, DebugProbeError> {
+    let n = mi.read_swo(scratch)?;
+    Ok(decoder.feed(&scratch[..n]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_instrumentation_packet() {
+        let mut decoder = TraceDecoder::new();
+        // Port 0, 1-byte payload: header = (0 << 3) | size(01).
+        let packets = decoder.feed(&[0b0000_0001, 0x42]);
+
+        assert_eq!(
+            packets,
+            vec![TracePacket::Instrumentation { port: 0, payload: vec![0x42] }]
+        );
+    }
+
+    #[test]
+    fn decodes_pc_sample_packet() {
+        let mut decoder = TraceDecoder::new();
+        // DWT source 2, 4-byte payload: header = (2 << 3) | hw(1 << 2) | size(11).
+        let packets = decoder.feed(&[0x17, 0x00, 0x00, 0x00, 0x20]);
+
+        assert_eq!(packets, vec![TracePacket::PcSample { pc: 0x2000_0000 }]);
+    }
+
+    #[test]
+    fn buffers_a_partial_frame_across_calls() {
+        let mut decoder = TraceDecoder::new();
+
+        // Header declares a 4-byte payload, but only 2 bytes have arrived.
+        assert_eq!(decoder.feed(&[0x17, 0x00, 0x00]), vec![]);
+
+        let packets = decoder.feed(&[0x00, 0x20]);
+        assert_eq!(packets, vec![TracePacket::PcSample { pc: 0x2000_0000 }]);
+    }
+
+    #[test]
+    fn decodes_unknown_sync_packet() {
+        let mut decoder = TraceDecoder::new();
+        let packets = decoder.feed(&[0x00]);
+
+        assert_eq!(packets, vec![TracePacket::Unknown(vec![0x00])]);
+    }
+}